@@ -3,6 +3,7 @@ use soroban_sdk::{contracttype, Address};
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum TransactionStatus {
+    PendingAcceptance,
     Held,
     HoldbackPending,
     Completed,
@@ -22,6 +23,19 @@ pub struct Transaction {
     pub final_amount: u128,
     pub release_time: u64,
     pub status: TransactionStatus,
+    pub seller_collateral: u128,
+    /// Conversion rate used to derive `holdback_amount`, for auditability; 1/1 when
+    /// `create_payment` set the holdback directly from a percentage.
+    pub rate_numerator: u128,
+    pub rate_denominator: u128,
+    pub disputed_party: Option<Address>,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConversionRate {
+    pub numerator: u128,
+    pub denominator: u128,
 }
 
 #[contracttype]
@@ -31,4 +45,10 @@ pub enum DataKey {
     TransactionCounter,
     Token,
     Admin,
+    FrozenAccount(Address),
+    OpenDisputes(Address),
+    AdverseResolutions(Address),
+    DustThreshold,
+    ConversionRate(Address),
+    AllowedToken(Address),
 }
@@ -18,4 +18,10 @@ pub enum Error {
     Paused = 13,
     TransferFailed = 14,
     InsufficientAllowance = 15,
+    FrozenAccount = 16,
+    AlreadyDisputed = 17,
+    NotDisputed = 18,
+    DustAmount = 19,
+    RateNotFound = 20,
+    InvalidToken = 21,
 }
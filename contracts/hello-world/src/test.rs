@@ -0,0 +1,457 @@
+use super::{HoldBackContract, HoldBackContractClient};
+use crate::entities::TransactionStatus;
+use crate::errors::Error;
+use soroban_sdk::{testutils::Address as _, token, Address, Env};
+
+fn create_token_contract<'a>(
+    env: &Env,
+    admin: &Address,
+) -> (Address, token::StellarAssetClient<'a>, token::Client<'a>) {
+    let contract_address = env.register_stellar_asset_contract(admin.clone());
+    (
+        contract_address.clone(),
+        token::StellarAssetClient::new(env, &contract_address),
+        token::Client::new(env, &contract_address),
+    )
+}
+
+fn setup<'a>(
+    dust_threshold: u128,
+) -> (
+    Env,
+    HoldBackContractClient<'a>,
+    Address,
+    Address,
+    token::StellarAssetClient<'a>,
+    token::Client<'a>,
+) {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let contract_id = env.register_contract(None, HoldBackContract);
+    let client = HoldBackContractClient::new(&env, &contract_id);
+
+    let (token_id, token_admin, token_client) = create_token_contract(&env, &admin);
+    client.initialize(&admin, &dust_threshold);
+    client.allow_token(&token_id, &admin);
+
+    (env, client, admin, token_id, token_admin, token_client)
+}
+
+#[test]
+fn dust_holdback_is_folded_into_final_amount() {
+    let (env, client, _admin, token_id, token_admin, token_client) = setup(10);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+
+    // holdback_rate of 5% on 100 yields holdback_amount == 5, below the dust threshold.
+    let tx_id = client.create_payment(&buyer, &seller, &100, &token_id, &5, &30, &0);
+    let transaction = client.get_transaction(&tx_id);
+
+    assert_eq!(transaction.holdback_amount, 0);
+    assert_eq!(transaction.final_amount, 100);
+}
+
+#[test]
+fn payment_is_rejected_when_the_final_amount_itself_is_dust() {
+    let (env, client, _admin, token_id, token_admin, _token_client) = setup(200);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+
+    // holdback folds into final_amount (100), which is still below the 200 dust_threshold.
+    let result = client.try_create_payment(&buyer, &seller, &100, &token_id, &5, &30, &0);
+    assert_eq!(result, Err(Ok(Error::DustAmount)));
+}
+
+#[test]
+fn resolve_dispute_rejects_shares_that_dont_sum_to_holdback() {
+    let (env, client, admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+    client.accept_payment(&tx_id, &seller);
+    client.initiate_dispute(&tx_id, &buyer);
+
+    let result = client.try_resolve_dispute(&tx_id, &100, &50, &0, &admin);
+    assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+}
+
+#[test]
+fn resolve_dispute_splits_proportionally_and_sets_status() {
+    let (env, client, admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &2_000);
+    token_client.approve(&buyer, &client.address, &2_000, &200);
+
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+    client.accept_payment(&tx_id, &seller);
+    client.initiate_dispute(&tx_id, &buyer);
+    client.resolve_dispute(&tx_id, &50, &150, &0, &admin);
+    let completed = client.get_transaction(&tx_id);
+    assert_eq!(completed.status, TransactionStatus::Completed);
+
+    let tx_id_2 = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+    client.accept_payment(&tx_id_2, &seller);
+    client.initiate_dispute(&tx_id_2, &buyer);
+    client.resolve_dispute(&tx_id_2, &200, &0, &0, &admin);
+    let cancelled = client.get_transaction(&tx_id_2);
+    assert_eq!(cancelled.status, TransactionStatus::Cancelled);
+}
+
+#[test]
+fn resolve_dispute_rejects_collateral_slash_above_bond() {
+    let (env, client, admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_admin.mint(&seller, &1_000);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+    token_client.approve(&seller, &client.address, &1_000, &200);
+
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &50);
+    client.accept_payment(&tx_id, &seller);
+    client.initiate_dispute(&tx_id, &buyer);
+
+    let result = client.try_resolve_dispute(&tx_id, &200, &0, &100, &admin);
+    assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+}
+
+#[test]
+fn seller_can_initiate_dispute_and_open_disputes_tracks_the_counterparty() {
+    let (env, client, _admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+    client.accept_payment(&tx_id, &seller);
+
+    client.initiate_dispute(&tx_id, &seller);
+    let transaction = client.get_transaction(&tx_id);
+    assert_eq!(transaction.status, TransactionStatus::Disputed);
+    // The seller initiated, so the buyer is the counterparty the dispute is tracked against.
+    assert_eq!(client.get_open_disputes(&buyer), 1);
+    assert_eq!(client.get_open_disputes(&seller), 0);
+}
+
+#[test]
+fn resolving_a_dispute_decrements_the_counterparty_open_disputes_count() {
+    let (env, client, admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+    client.accept_payment(&tx_id, &seller);
+
+    client.initiate_dispute(&tx_id, &buyer);
+    assert_eq!(client.get_open_disputes(&seller), 1);
+
+    client.resolve_dispute(&tx_id, &200, &0, &0, &admin);
+    assert_eq!(client.get_open_disputes(&seller), 0);
+}
+
+#[test]
+fn adverse_resolutions_against_the_seller_accumulate_and_never_decrement() {
+    let (env, client, admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &4_000);
+    token_client.approve(&buyer, &client.address, &4_000, &200);
+
+    // Resolved fully in the buyer's favor: adverse to the seller.
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+    client.accept_payment(&tx_id, &seller);
+    client.initiate_dispute(&tx_id, &buyer);
+    client.resolve_dispute(&tx_id, &200, &0, &0, &admin);
+    assert_eq!(client.get_adverse_resolutions(&seller), 1);
+    // Unlike get_open_disputes, this lifetime counter survives the dispute resolving.
+    assert_eq!(client.get_open_disputes(&seller), 0);
+
+    // Resolved fully in the seller's favor: not adverse, counter holds steady.
+    let tx_id_2 = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+    client.accept_payment(&tx_id_2, &seller);
+    client.initiate_dispute(&tx_id_2, &buyer);
+    client.resolve_dispute(&tx_id_2, &0, &200, &0, &admin);
+    assert_eq!(client.get_adverse_resolutions(&seller), 1);
+
+    // A second adverse resolution keeps the lifetime counter climbing.
+    let tx_id_3 = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+    client.accept_payment(&tx_id_3, &seller);
+    client.initiate_dispute(&tx_id_3, &buyer);
+    client.resolve_dispute(&tx_id_3, &200, &0, &0, &admin);
+    assert_eq!(client.get_adverse_resolutions(&seller), 2);
+}
+
+#[test]
+fn disputing_twice_is_rejected_and_resolving_without_a_dispute_is_rejected() {
+    let (env, client, admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+    client.accept_payment(&tx_id, &seller);
+
+    let premature = client.try_resolve_dispute(&tx_id, &200, &0, &0, &admin);
+    assert_eq!(premature, Err(Ok(Error::NotDisputed)));
+
+    client.initiate_dispute(&tx_id, &buyer);
+    let again = client.try_initiate_dispute(&tx_id, &seller);
+    assert_eq!(again, Err(Ok(Error::AlreadyDisputed)));
+}
+
+#[test]
+fn create_payment_rejects_a_disallowed_token() {
+    let (env, client, admin, token_id, token_admin, _token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+
+    client.disallow_token(&token_id, &admin);
+    let result = client.try_create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+    assert_eq!(result, Err(Ok(Error::InvalidToken)));
+}
+
+#[test]
+fn create_payment_rejects_an_allowlisted_address_that_isnt_a_token() {
+    let (env, client, admin, _token_id, _token_admin, _token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    let not_a_token = Address::generate(&env);
+
+    client.allow_token(&not_a_token, &admin);
+    let result = client.try_create_payment(&buyer, &seller, &1_000, &not_a_token, &20, &30, &0);
+    assert_eq!(result, Err(Ok(Error::InvalidToken)));
+}
+
+#[test]
+fn create_payment_in_reference_converts_using_the_stored_rate() {
+    let (env, client, admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+
+    // 1 reference unit == 2 token units: token_units = ref_units * denominator / numerator.
+    client.set_conversion_rate(&token_id, &1, &2, &admin);
+    let tx_id =
+        client.create_payment_in_reference(&buyer, &seller, &1_000, &token_id, &100, &30, &0);
+    let transaction = client.get_transaction(&tx_id);
+
+    assert_eq!(transaction.holdback_amount, 200);
+    assert_eq!(transaction.final_amount, 800);
+}
+
+#[test]
+fn create_payment_in_reference_requires_a_registered_rate() {
+    let (env, client, _admin, token_id, token_admin, _token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+
+    let result =
+        client.try_create_payment_in_reference(&buyer, &seller, &1_000, &token_id, &100, &30, &0);
+    assert_eq!(result, Err(Ok(Error::RateNotFound)));
+}
+
+#[test]
+fn removed_conversion_rate_is_no_longer_usable() {
+    let (env, client, admin, token_id, token_admin, _token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+
+    client.set_conversion_rate(&token_id, &1, &2, &admin);
+    client.remove_conversion_rate(&token_id, &admin);
+
+    let result =
+        client.try_create_payment_in_reference(&buyer, &seller, &1_000, &token_id, &100, &30, &0);
+    assert_eq!(result, Err(Ok(Error::RateNotFound)));
+}
+
+#[test]
+fn frozen_accounts_cannot_have_payments_created_against_them() {
+    let (env, client, admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+
+    client.freeze_account(&seller, &admin);
+    let result = client.try_create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+    assert_eq!(result, Err(Ok(Error::FrozenAccount)));
+
+    client.unfreeze_account(&seller, &admin);
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+    assert_eq!(
+        client.get_transaction(&tx_id).status,
+        TransactionStatus::PendingAcceptance
+    );
+}
+
+#[test]
+fn accept_payment_pulls_collateral_and_pays_out_the_final_amount() {
+    let (env, client, _admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_admin.mint(&seller, &1_000);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+    token_client.approve(&seller, &client.address, &1_000, &200);
+
+    // 20% of 1_000 held back, 800 final_amount; 100 collateral bonded by the seller.
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &100);
+    client.accept_payment(&tx_id, &seller);
+
+    assert_eq!(
+        client.get_transaction(&tx_id).status,
+        TransactionStatus::Held
+    );
+    assert_eq!(token_client.balance(&seller), 1_000 - 100 + 800);
+}
+
+#[test]
+fn accept_payment_rejects_unauthorized_caller_and_double_accept() {
+    let (env, client, _admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    let stranger = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+
+    let unauthorized = client.try_accept_payment(&tx_id, &stranger);
+    assert_eq!(unauthorized, Err(Ok(Error::Unauthorized)));
+
+    client.accept_payment(&tx_id, &seller);
+    let double_accept = client.try_accept_payment(&tx_id, &seller);
+    assert_eq!(double_accept, Err(Ok(Error::InvalidStatus)));
+}
+
+#[test]
+fn accept_payment_rejects_insufficient_seller_collateral_balance() {
+    let (env, client, _admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &100);
+    let result = client.try_accept_payment(&tx_id, &seller);
+    assert_eq!(result, Err(Ok(Error::InsufficientBalance)));
+}
+
+#[test]
+fn cancel_payment_refunds_the_buyer_and_rejects_non_buyer_or_wrong_status() {
+    let (env, client, _admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    let stranger = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &0);
+    assert_eq!(token_client.balance(&buyer), 0);
+
+    let unauthorized = client.try_cancel_payment(&tx_id, &stranger);
+    assert_eq!(unauthorized, Err(Ok(Error::Unauthorized)));
+
+    client.cancel_payment(&tx_id, &buyer);
+    assert_eq!(
+        client.get_transaction(&tx_id).status,
+        TransactionStatus::Cancelled
+    );
+    assert_eq!(token_client.balance(&buyer), 1_000);
+
+    let already_cancelled = client.try_cancel_payment(&tx_id, &buyer);
+    assert_eq!(already_cancelled, Err(Ok(Error::InvalidStatus)));
+}
+
+#[test]
+fn approve_release_pays_out_holdback_and_collateral_to_seller() {
+    let (env, client, _admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_admin.mint(&seller, &100);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+    token_client.approve(&seller, &client.address, &100, &200);
+
+    // 20% of 1_000 held back (200), final_amount 800; 100 collateral bonded by the seller.
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &100);
+    client.accept_payment(&tx_id, &seller);
+    assert_eq!(token_client.balance(&seller), 100 - 100 + 800);
+
+    client.approve_release(&tx_id, &buyer);
+
+    let transaction = client.get_transaction(&tx_id);
+    assert_eq!(transaction.status, TransactionStatus::Completed);
+    assert_eq!(transaction.seller_collateral, 0);
+    // Seller now also has the 200 holdback plus their 100 collateral back.
+    assert_eq!(token_client.balance(&seller), 800 + 200 + 100);
+}
+
+#[test]
+fn resolve_dispute_with_collateral_slash_moves_the_right_balances() {
+    let (env, client, admin, token_id, token_admin, token_client) = setup(1);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_admin.mint(&seller, &50);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+    token_client.approve(&seller, &client.address, &50, &200);
+
+    let tx_id = client.create_payment(&buyer, &seller, &1_000, &token_id, &20, &30, &50);
+    client.accept_payment(&tx_id, &seller);
+    client.initiate_dispute(&tx_id, &buyer);
+
+    // holdback_amount (200) splits 50/150, and 20 of the 50 collateral is slashed to the buyer.
+    client.resolve_dispute(&tx_id, &50, &150, &20, &admin);
+
+    assert_eq!(
+        client.get_transaction(&tx_id).status,
+        TransactionStatus::Completed
+    );
+    // buyer had paid the full 1_000 in; now refunded their 50 share plus the 20 slash.
+    assert_eq!(token_client.balance(&buyer), 50 + 20);
+    // seller: post-accept balance (50 - 50 collateral + 800 final) + 150 share + 30 remaining collateral.
+    assert_eq!(token_client.balance(&seller), (50 - 50 + 800) + 150 + 30);
+}
+
+#[test]
+fn resolve_dispute_on_a_dust_folded_payment_completes_when_seller_keeps_their_collateral() {
+    let (env, client, admin, token_id, token_admin, token_client) = setup(10);
+    let buyer = Address::generate(&env);
+    let seller = Address::generate(&env);
+    token_admin.mint(&buyer, &1_000);
+    token_admin.mint(&seller, &50);
+    token_client.approve(&buyer, &client.address, &1_000, &200);
+    token_client.approve(&seller, &client.address, &50, &200);
+
+    // holdback_rate of 5% on 100 folds into final_amount, leaving holdback_amount == 0.
+    let tx_id = client.create_payment(&buyer, &seller, &100, &token_id, &5, &30, &50);
+    client.accept_payment(&tx_id, &seller);
+    client.initiate_dispute(&tx_id, &buyer);
+
+    // Shares must sum to the (zero) holdback_amount; the admin leaves the seller's collateral
+    // untouched, so this resolution is entirely in the seller's favor.
+    client.resolve_dispute(&tx_id, &0, &0, &0, &admin);
+
+    assert_eq!(
+        client.get_transaction(&tx_id).status,
+        TransactionStatus::Completed
+    );
+}